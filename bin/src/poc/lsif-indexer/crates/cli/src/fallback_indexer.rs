@@ -0,0 +1,240 @@
+//! LSPサーバーが使えない場合のバックアップ用シンボル抽出
+//!
+//! 以前は行ベースの正規表現・文字列ヒューリスティックで `myFunction = x: x + 1;`
+//! のようなバインディングを拾っていたため、文字列リテラルの中の `=` や
+//! コメント中の `:` を誤検出したり、`let ... in` にネストしたバインディングを
+//! 取りこぼしたりしていた。ここでは `rnix`/`rowan`（nixdや `nil` と同じ
+//! rowan 0.15ベースの具象構文木）でファイル全体をパースし、`AttrSet`・
+//! `LetIn`・`Lambda` のパラメータ（パターン分解を含む）・`inherit (x) a b;`
+//! 節を木として正確に辿ってシンボルを作る。
+use anyhow::Result;
+use lsif_core::{Position, Range, Symbol, SymbolKind};
+use rnix::ast::{self, HasEntry};
+use rowan::ast::AstNode;
+
+/// フォールバックインデクサが対応する言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackLanguage {
+    Nix,
+}
+
+/// LSPサーバーなしで動く、構文木ベースの最小インデクサ
+pub struct FallbackIndexer {
+    language: FallbackLanguage,
+}
+
+impl FallbackIndexer {
+    pub fn new(language: FallbackLanguage) -> Self {
+        Self { language }
+    }
+
+    /// Nixソースからバインディングのシンボルを抽出する
+    ///
+    /// `lines` はエディタのバッファ表現に合わせた行配列。内部では改行で
+    /// 結合し直し、`rnix` でパースしてから構文木を辿る。
+    pub fn extract_nix_symbols(&self, lines: &[&str]) -> Result<Vec<Symbol>> {
+        debug_assert_eq!(self.language, FallbackLanguage::Nix);
+        let source = lines.join("\n");
+        Ok(extract_nix_symbols_from_source(&source))
+    }
+}
+
+fn extract_nix_symbols_from_source(source: &str) -> Vec<Symbol> {
+    let parse = rnix::Root::parse(source);
+    let root = parse.tree();
+
+    let mut symbols = Vec::new();
+    if let Some(expr) = root.expr() {
+        walk_expr(&expr, source, &mut symbols);
+    }
+    symbols
+}
+
+/// 式を再帰的に辿り、バインディングをシンボルとして集める
+fn walk_expr(expr: &ast::Expr, source: &str, symbols: &mut Vec<Symbol>) {
+    match expr {
+        // `rec` の有無に関わらず、属性セットは同じ `HasEntry` 経由で辿れる
+        ast::Expr::AttrSet(attr_set) => walk_bindings(attr_set, source, symbols),
+        ast::Expr::LetIn(let_in) => {
+            walk_bindings(let_in, source, symbols);
+            if let Some(body) = let_in.body() {
+                walk_expr(&body, source, symbols);
+            }
+        }
+        ast::Expr::Lambda(lambda) => {
+            if let Some(param) = lambda.param() {
+                walk_param(&param, source, symbols);
+            }
+            if let Some(body) = lambda.body() {
+                walk_expr(&body, source, symbols);
+            }
+        }
+        ast::Expr::Paren(paren) => {
+            if let Some(inner) = paren.expr() {
+                walk_expr(&inner, source, symbols);
+            }
+        }
+        ast::Expr::Apply(apply) => {
+            if let Some(f) = apply.lambda() {
+                walk_expr(&f, source, symbols);
+            }
+            if let Some(a) = apply.argument() {
+                walk_expr(&a, source, symbols);
+            }
+        }
+        ast::Expr::With(with) => {
+            if let Some(body) = with.body() {
+                walk_expr(&body, source, symbols);
+            }
+        }
+        ast::Expr::IfElse(if_else) => {
+            if let Some(body) = if_else.body() {
+                walk_expr(&body, source, symbols);
+            }
+            if let Some(else_body) = if_else.else_body() {
+                walk_expr(&else_body, source, symbols);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `AttrSet`/`LetIn` いずれも実装している `HasEntry` を介して
+/// `attrpath = value;` と `inherit ...;` の両方からシンボルを作る
+fn walk_bindings<N: HasEntry>(node: &N, source: &str, symbols: &mut Vec<Symbol>) {
+    for entry in node.attrpath_values() {
+        let (Some(attrpath), Some(value)) = (entry.attrpath(), entry.value()) else {
+            continue;
+        };
+
+        // `devShells.default = ...;`は`devShells = { default = ...; };`の脱糖
+        // なので、実際にバインドされるのは先頭のセグメント（`devShells`）で
+        // あり、末尾（`default`）ではない
+        if let Some(head) = attrpath.attrs().next() {
+            if let Some(name) = attr_name(&head) {
+                let kind = if matches!(value, ast::Expr::Lambda(_)) {
+                    SymbolKind::Function
+                } else {
+                    SymbolKind::Variable
+                };
+                symbols.push(make_symbol(&name, kind, head.syntax().text_range(), source));
+            }
+        }
+
+        walk_expr(&value, source, symbols);
+    }
+
+    for inherit in node.inherits() {
+        for attr in inherit.attrs() {
+            if let Some(name) = attr_name(&attr) {
+                symbols.push(make_symbol(
+                    &name,
+                    SymbolKind::Variable,
+                    attr.syntax().text_range(),
+                    source,
+                ));
+            }
+        }
+    }
+}
+
+/// `Lambda` のパラメータからシンボルを作る。`{ a, b ? default, ... }` の
+/// パターン分解では、各エントリ名と `@`束縛名の両方を拾い、`...` は
+/// 名前を持たないため読み飛ばす。デフォルト式の中にもバインディングが
+/// あり得るので再帰的に辿る。
+fn walk_param(param: &ast::Param, source: &str, symbols: &mut Vec<Symbol>) {
+    match param {
+        ast::Param::IdentParam(ident_param) => {
+            if let Some(ident) = ident_param.ident() {
+                push_ident_symbol(&ident, source, symbols);
+            }
+        }
+        ast::Param::Pattern(pattern) => {
+            if let Some(bind) = pattern.pat_bind() {
+                if let Some(ident) = bind.ident() {
+                    push_ident_symbol(&ident, source, symbols);
+                }
+            }
+
+            for entry in pattern.pat_entries() {
+                if let Some(ident) = entry.ident() {
+                    push_ident_symbol(&ident, source, symbols);
+                }
+                if let Some(default) = entry.default() {
+                    walk_expr(&default, source, symbols);
+                }
+            }
+        }
+    }
+}
+
+fn push_ident_symbol(ident: &ast::Ident, source: &str, symbols: &mut Vec<Symbol>) {
+    if let Some(token) = ident.ident_token() {
+        symbols.push(make_symbol(
+            token.text(),
+            SymbolKind::Variable,
+            ident.syntax().text_range(),
+            source,
+        ));
+    }
+}
+
+fn attr_name(attr: &ast::Attr) -> Option<String> {
+    match attr {
+        ast::Attr::Ident(ident) => ident.ident_token().map(|t| t.text().to_string()),
+        // 動的属性（`${expr} = ...;`）や文字列属性は静的な名前を持たないため
+        // フォールバック抽出の対象外とする
+        ast::Attr::Dynamic(_) | ast::Attr::Str(_) => None,
+    }
+}
+
+fn make_symbol(name: &str, kind: SymbolKind, range: rowan::TextRange, source: &str) -> Symbol {
+    let start = offset_to_position(source, usize::from(range.start()));
+    let end = offset_to_position(source, usize::from(range.end()));
+
+    Symbol {
+        id: format!("{}@{}:{}", name, start.line, start.character),
+        name: name.to_string(),
+        kind,
+        range: Range { start, end },
+        file_path: String::new(),
+        signature: None,
+        documentation: None,
+    }
+}
+
+/// バイトオフセットをLSPのUTF-16位置に変換する（`export_lsif` と同じ
+/// `positionEncoding: "utf-16"` に合わせる）
+fn offset_to_position(source: &str, byte_offset: usize) -> Position {
+    let mut line = 0u32;
+    let mut character = 0u32;
+
+    for (idx, ch) in source.char_indices() {
+        if idx >= byte_offset {
+            break;
+        }
+        if ch == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += ch.len_utf16() as u32;
+        }
+    }
+
+    Position { line, character }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dotted_attrpath_yields_symbol_for_its_first_segment() {
+        let lines = ["let devShells.default = 1; in devShells"];
+        let symbols = FallbackIndexer::new(FallbackLanguage::Nix)
+            .extract_nix_symbols(&lines)
+            .unwrap();
+        assert!(symbols.iter().any(|s| s.name == "devShells"));
+        assert!(!symbols.iter().any(|s| s.name == "default"));
+    }
+}
@@ -1,7 +1,9 @@
 //! 汎用Indexerの結果を永続化するヘルパー
-use anyhow::Result;
-use lsif_core::CodeGraph;
+use anyhow::{Context, Result};
+use lsif_core::{CodeGraph, Position, Range, Symbol, SymbolKind};
 use crate::storage::IndexStorage;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
 use std::path::Path;
 
 /// Indexerの結果を永続化
@@ -16,20 +18,500 @@ pub fn persist_index_result(
 }
 
 /// 永続化されたグラフを読み込み
+///
+/// 読み取り専用・マルチリーダーの `open_read_only` を経由するため、書き込み側
+/// （エディタプロセスなど）がストアを保持していても並行して読み込める。
 pub fn load_index_result(
     storage_path: &Path,
     key: &str
 ) -> Result<Option<CodeGraph>> {
-    let storage = IndexStorage::open(storage_path)?;
+    let storage = IndexStorage::open_read_only(storage_path)
+        .context("failed to open index storage in read-only mode")?;
     storage.load_graph(key)
 }
 
 /// シンボル検索（永続化データから）
+///
+/// [`load_index_result`] と同様、読み取り専用ハンドルを使う。
 pub fn search_symbols(
     storage_path: &Path,
     key: &str,
     pattern: &str
 ) -> Result<Vec<lsif_core::Symbol>> {
-    let storage = IndexStorage::open(storage_path)?;
+    let storage = IndexStorage::open_read_only(storage_path)
+        .context("failed to open index storage in read-only mode")?;
     storage.search_symbols(key, pattern)
 }
+
+/// LSIFダンプの1行（vertexまたはedge）に割り振る連番ID
+struct LsifIdGen {
+    next_id: u64,
+}
+
+impl LsifIdGen {
+    fn new() -> Self {
+        Self { next_id: 1 }
+    }
+
+    fn next(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+/// `CodeGraph` を標準的なLSIFダンプ（line-delimited JSON）として書き出す
+///
+/// 出力は `metaData` vertex、ファイルごとの `document` vertex、シンボルごとの
+/// `range` vertex、`resultSet`/`moniker`/`definitionResult`/`referenceResult`/`hoverResult`
+/// vertex と、それらを結ぶ `next`/`moniker`/`textDocument/definition`/`textDocument/references`/
+/// `textDocument/hover`/`item`/`contains` edge からなる。エディタや他のLSIF対応ビューアがそのまま読み込める形式。
+pub fn export_lsif(graph: &CodeGraph, out: &mut impl Write) -> Result<()> {
+    let mut ids = LsifIdGen::new();
+
+    let meta_id = ids.next();
+    write_line(
+        out,
+        &serde_json::json!({
+            "id": meta_id,
+            "type": "vertex",
+            "label": "metaData",
+            "version": "0.4.3",
+            "positionEncoding": "utf-16",
+            "toolInfo": { "name": "lsif-indexer" }
+        }),
+    )?;
+
+    // ファイルごとにdocument vertexを作り、そのファイルのシンボルをまとめる
+    let mut symbols_by_file: std::collections::BTreeMap<String, Vec<&Symbol>> =
+        std::collections::BTreeMap::new();
+    for symbol in graph.symbols() {
+        symbols_by_file
+            .entry(symbol.file_path.clone())
+            .or_default()
+            .push(symbol);
+    }
+
+    for (file_path, symbols) in &symbols_by_file {
+        let document_id = ids.next();
+        let uri = if file_path.starts_with("file://") {
+            file_path.clone()
+        } else {
+            format!("file://{}", file_path)
+        };
+        write_line(
+            out,
+            &serde_json::json!({
+                "id": document_id,
+                "type": "vertex",
+                "label": "document",
+                "uri": uri,
+                "languageId": language_id_for_path(file_path),
+            }),
+        )?;
+
+        for symbol in symbols {
+            let range_id = ids.next();
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": range_id,
+                    "type": "vertex",
+                    "label": "range",
+                    "start": { "line": symbol.range.start.line, "character": symbol.range.start.character },
+                    "end": { "line": symbol.range.end.line, "character": symbol.range.end.character },
+                }),
+            )?;
+
+            let result_set_id = ids.next();
+            write_line(
+                out,
+                &serde_json::json!({ "id": result_set_id, "type": "vertex", "label": "resultSet" }),
+            )?;
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": ids.next(),
+                    "type": "edge",
+                    "label": "next",
+                    "outV": range_id,
+                    "inV": result_set_id,
+                }),
+            )?;
+
+            // `moniker` vertexに `kind:name` を identifier として載せておくと、
+            // `import_lsif` が実際のシンボル名と種別を復元できる（種別は
+            // Function/Variableのみを区別し、それ以外はVariableとして書き出す）。標準LSIFの
+            // monikerは本来クロスリポジトリ識別用だが、ここではシリアライズ
+            // のために流用する。
+            let moniker_id = ids.next();
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": moniker_id,
+                    "type": "vertex",
+                    "label": "moniker",
+                    "kind": "export",
+                    "scheme": "lsif-indexer",
+                    "identifier": format!("{}:{}", symbol_kind_to_str(&symbol.kind), symbol.name),
+                }),
+            )?;
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": ids.next(),
+                    "type": "edge",
+                    "label": "moniker",
+                    "outV": result_set_id,
+                    "inV": moniker_id,
+                }),
+            )?;
+
+            if let Some(hover) = hover_contents(symbol) {
+                let hover_id = ids.next();
+                write_line(
+                    out,
+                    &serde_json::json!({
+                        "id": hover_id,
+                        "type": "vertex",
+                        "label": "hoverResult",
+                        "result": { "contents": hover },
+                    }),
+                )?;
+                write_line(
+                    out,
+                    &serde_json::json!({
+                        "id": ids.next(),
+                        "type": "edge",
+                        "label": "textDocument/hover",
+                        "outV": result_set_id,
+                        "inV": hover_id,
+                    }),
+                )?;
+            }
+
+            let definition_result_id = ids.next();
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": definition_result_id,
+                    "type": "vertex",
+                    "label": "definitionResult",
+                }),
+            )?;
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": ids.next(),
+                    "type": "edge",
+                    "label": "textDocument/definition",
+                    "outV": result_set_id,
+                    "inV": definition_result_id,
+                }),
+            )?;
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": ids.next(),
+                    "type": "edge",
+                    "label": "item",
+                    "outV": definition_result_id,
+                    "inVs": [range_id],
+                    "document": document_id,
+                }),
+            )?;
+
+            let reference_ranges: Vec<u64> = graph
+                .references_to(&symbol.id)
+                .map(|reference| {
+                    let reference_range_id = ids.next();
+                    write_line(
+                        out,
+                        &serde_json::json!({
+                            "id": reference_range_id,
+                            "type": "vertex",
+                            "label": "range",
+                            "start": { "line": reference.range.start.line, "character": reference.range.start.character },
+                            "end": { "line": reference.range.end.line, "character": reference.range.end.character },
+                        }),
+                    ).ok();
+                    reference_range_id
+                })
+                .collect();
+
+            if !reference_ranges.is_empty() {
+                let reference_result_id = ids.next();
+                write_line(
+                    out,
+                    &serde_json::json!({
+                        "id": reference_result_id,
+                        "type": "vertex",
+                        "label": "referenceResult",
+                    }),
+                )?;
+                write_line(
+                    out,
+                    &serde_json::json!({
+                        "id": ids.next(),
+                        "type": "edge",
+                        "label": "textDocument/references",
+                        "outV": result_set_id,
+                        "inV": reference_result_id,
+                    }),
+                )?;
+                write_line(
+                    out,
+                    &serde_json::json!({
+                        "id": ids.next(),
+                        "type": "edge",
+                        "label": "item",
+                        "outV": reference_result_id,
+                        "inVs": reference_ranges,
+                        "document": document_id,
+                        "property": "references",
+                    }),
+                )?;
+            }
+
+            write_line(
+                out,
+                &serde_json::json!({
+                    "id": ids.next(),
+                    "type": "edge",
+                    "label": "contains",
+                    "outV": document_id,
+                    "inVs": [range_id],
+                }),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `export_lsif` が書き出したダンプを読み込み、`CodeGraph` を復元する
+///
+/// `range`/`resultSet`/`moniker`/`document` vertexと、`next`/`moniker`/`contains`
+/// edgeを組み合わせて各シンボルの名前・種別・位置・所属ファイルを復元する
+/// （`export_lsif` がこの構造で書き出すことを前提にしている）。`hoverResult`が
+/// あればdocumentationとして復元するが、なくてもシンボル自体は復元できる。
+pub fn import_lsif(input: impl BufRead) -> Result<CodeGraph> {
+    let mut graph = CodeGraph::new();
+
+    let mut range_by_id: HashMap<u64, Range> = HashMap::new();
+    let mut document_uri_by_id: HashMap<u64, String> = HashMap::new();
+    let mut moniker_identifier_by_id: HashMap<u64, String> = HashMap::new();
+    let mut hover_by_id: HashMap<u64, String> = HashMap::new();
+
+    let mut result_set_by_range: HashMap<u64, u64> = HashMap::new();
+    let mut moniker_by_result_set: HashMap<u64, u64> = HashMap::new();
+    let mut hover_by_result_set: HashMap<u64, u64> = HashMap::new();
+    let mut document_by_range: HashMap<u64, u64> = HashMap::new();
+
+    for line in input.lines() {
+        let line = line.context("failed to read LSIF dump line")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: serde_json::Value =
+            serde_json::from_str(&line).context("invalid LSIF JSON line")?;
+
+        let Some(id) = value.get("id").and_then(|v| v.as_u64()) else {
+            continue;
+        };
+
+        match value.get("type").and_then(|t| t.as_str()) {
+            Some("vertex") => match value.get("label").and_then(|l| l.as_str()) {
+                Some("range") => {
+                    if let Some(range) = parse_range(&value) {
+                        range_by_id.insert(id, range);
+                    }
+                }
+                Some("document") => {
+                    if let Some(uri) = value.get("uri").and_then(|u| u.as_str()) {
+                        document_uri_by_id.insert(id, uri.to_string());
+                    }
+                }
+                Some("moniker") => {
+                    if let Some(identifier) = value.get("identifier").and_then(|i| i.as_str()) {
+                        moniker_identifier_by_id.insert(id, identifier.to_string());
+                    }
+                }
+                Some("hoverResult") => {
+                    if let Some(contents) = value
+                        .pointer("/result/contents")
+                        .and_then(|c| c.as_str())
+                    {
+                        hover_by_id.insert(id, contents.to_string());
+                    }
+                }
+                _ => {}
+            },
+            Some("edge") => match value.get("label").and_then(|l| l.as_str()) {
+                Some("next") => {
+                    if let (Some(out_v), Some(in_v)) = (edge_out(&value), edge_in(&value)) {
+                        result_set_by_range.insert(out_v, in_v);
+                    }
+                }
+                Some("moniker") => {
+                    if let (Some(out_v), Some(in_v)) = (edge_out(&value), edge_in(&value)) {
+                        moniker_by_result_set.insert(out_v, in_v);
+                    }
+                }
+                Some("textDocument/hover") => {
+                    if let (Some(out_v), Some(in_v)) = (edge_out(&value), edge_in(&value)) {
+                        hover_by_result_set.insert(out_v, in_v);
+                    }
+                }
+                Some("contains") => {
+                    if let Some(document_id) = value.get("outV").and_then(|v| v.as_u64()) {
+                        for range_id in value
+                            .get("inVs")
+                            .and_then(|v| v.as_array())
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|v| v.as_u64())
+                        {
+                            document_by_range.insert(range_id, document_id);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    for (range_id, range) in &range_by_id {
+        let Some(result_set_id) = result_set_by_range.get(range_id) else {
+            continue;
+        };
+        let Some(moniker_id) = moniker_by_result_set.get(result_set_id) else {
+            continue;
+        };
+        let Some(identifier) = moniker_identifier_by_id.get(moniker_id) else {
+            continue;
+        };
+        let Some((kind, name)) = identifier.split_once(':') else {
+            continue;
+        };
+
+        let file_path = document_by_range
+            .get(range_id)
+            .and_then(|document_id| document_uri_by_id.get(document_id))
+            .map(|uri| uri.trim_start_matches("file://").to_string())
+            .unwrap_or_default();
+
+        let documentation = hover_by_result_set
+            .get(result_set_id)
+            .and_then(|hover_id| hover_by_id.get(hover_id))
+            .cloned();
+
+        graph.add_symbol(Symbol {
+            id: format!("{}@{}:{}", name, range.start.line, range.start.character),
+            name: name.to_string(),
+            kind: symbol_kind_from_str(kind),
+            range: range.clone(),
+            file_path,
+            signature: None,
+            documentation,
+        });
+    }
+
+    Ok(graph)
+}
+
+fn parse_range(vertex: &serde_json::Value) -> Option<Range> {
+    let start = vertex.get("start")?;
+    let end = vertex.get("end")?;
+    Some(Range {
+        start: Position {
+            line: start.get("line")?.as_u64()? as u32,
+            character: start.get("character")?.as_u64()? as u32,
+        },
+        end: Position {
+            line: end.get("line")?.as_u64()? as u32,
+            character: end.get("character")?.as_u64()? as u32,
+        },
+    })
+}
+
+fn edge_out(edge: &serde_json::Value) -> Option<u64> {
+    edge.get("outV").and_then(|v| v.as_u64())
+}
+
+fn edge_in(edge: &serde_json::Value) -> Option<u64> {
+    edge.get("inV").and_then(|v| v.as_u64())
+}
+
+fn symbol_kind_to_str(kind: &SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Function => "Function",
+        SymbolKind::Variable => "Variable",
+        _ => "Variable",
+    }
+}
+
+fn symbol_kind_from_str(kind: &str) -> SymbolKind {
+    match kind {
+        "Function" => SymbolKind::Function,
+        _ => SymbolKind::Variable,
+    }
+}
+
+fn write_line(out: &mut impl Write, value: &serde_json::Value) -> Result<()> {
+    serde_json::to_writer(&mut *out, value).context("failed to serialize LSIF vertex/edge")?;
+    out.write_all(b"\n").context("failed to write LSIF line")?;
+    Ok(())
+}
+
+fn hover_contents(symbol: &Symbol) -> Option<String> {
+    symbol.documentation.clone()
+}
+
+fn language_id_for_path(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("rs") => "rust",
+        Some("ts") | Some("tsx") => "typescript",
+        Some("js") | Some("jsx") => "javascript",
+        Some("py") => "python",
+        Some("go") => "go",
+        Some("java") => "java",
+        Some("nix") => "nix",
+        _ => "plaintext",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsif_core::SymbolKind;
+
+    #[test]
+    fn test_import_lsif_reconstructs_symbols_exported_by_export_lsif() {
+        let mut graph = CodeGraph::new();
+        graph.add_symbol(Symbol {
+            id: "foo@0:0".to_string(),
+            name: "foo".to_string(),
+            kind: SymbolKind::Function,
+            range: Range {
+                start: Position { line: 0, character: 0 },
+                end: Position { line: 0, character: 3 },
+            },
+            file_path: "src/a.rs".to_string(),
+            signature: None,
+            documentation: Some("does a thing".to_string()),
+        });
+
+        let mut dump = Vec::new();
+        export_lsif(&graph, &mut dump).unwrap();
+
+        let imported = import_lsif(dump.as_slice()).unwrap();
+        let symbols: Vec<&Symbol> = imported.symbols().collect();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "foo");
+        assert!(matches!(symbols[0].kind, SymbolKind::Function));
+        assert_eq!(symbols[0].file_path, "src/a.rs");
+        assert_eq!(symbols[0].documentation.as_deref(), Some("does a thing"));
+    }
+}
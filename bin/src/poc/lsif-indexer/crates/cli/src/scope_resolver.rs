@@ -0,0 +1,350 @@
+//! `with`/`inherit`を考慮したNixのスコープ解析
+//!
+//! `build_reference_pattern`が作る `\bname\b` の字句マッチは、`with pkgs;`や
+//! `let ... in`が使用箇所に現れないテキストでスコープに名前を導入することを
+//! 考慮できず、束縛のない誤検出や間違った束縛への結びつけを生む。ここでは
+//! [`crate::fallback_indexer`]と同じrnix構文木をスコープのスタックを積みながら
+//! 辿り、各識別子の参照が「どの束縛を指しているか」を求める。
+//!
+//! `let`/`rec`属性セット/ラムダ引数による字句束縛は、`with`よりも常に優先される
+//! （Nix自身のスコープ規則で、`with`は他のどの場所でも束縛されていない名前だけを
+//! 補う）。そのため解決は「スタック全体から字句束縛を探す」→「見つからなければ
+//! 最も内側の`with`に帰属させる」の2段階で行う。
+use rnix::ast::{self, HasEntry};
+use rowan::ast::AstNode;
+use rowan::TextRange;
+use std::collections::HashMap;
+
+/// スコープスタックの1段
+enum Scope {
+    /// `let`/`rec`属性セット/ラムダ引数による明示的な束縛。名前 -> 束縛範囲
+    Bindings(HashMap<String, TextRange>),
+    /// `with <expr>;` による動的スコープ。`expr_text`はその式のソーステキストで、
+    /// 具体的な属性名への解決はeval込みの意味解析モードに委ねる
+    With { expr_text: String },
+}
+
+/// 識別子の参照がどこに解決されたか
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// 同じ構文木内の明示的な束縛（`let`/`rec`/`inherit`/ラムダ引数）の範囲
+    Binding(TextRange),
+    /// 最も内側の`with <expr>;`に帰属。どの属性を指すかは評価しないと分からない
+    WithScope { expr_text: String },
+    /// 字句束縛も`with`もない（グローバルまたは未解決）
+    Unresolved,
+}
+
+/// 1つの識別子参照とその解決結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedReference {
+    pub name: String,
+    pub reference_range: TextRange,
+    pub resolution: Resolution,
+}
+
+/// Nixソース全体を解析し、すべての識別子参照を解決する
+pub fn resolve_references(source: &str) -> Vec<ResolvedReference> {
+    let parse = rnix::Root::parse(source);
+    let root = parse.tree();
+
+    let mut scopes: Vec<Scope> = Vec::new();
+    let mut references = Vec::new();
+    if let Some(expr) = root.expr() {
+        walk(&expr, &mut scopes, &mut references);
+    }
+    references
+}
+
+fn walk(expr: &ast::Expr, scopes: &mut Vec<Scope>, references: &mut Vec<ResolvedReference>) {
+    match expr {
+        ast::Expr::Ident(ident) => {
+            if let Some(token) = ident.ident_token() {
+                let name = token.text().to_string();
+                let reference_range = ident.syntax().text_range();
+                let resolution = resolve_name(&name, scopes);
+                references.push(ResolvedReference {
+                    name,
+                    reference_range,
+                    resolution,
+                });
+            }
+        }
+        // `rec`の属性セットだけが自身の名前を相互参照できるスコープを持つ
+        ast::Expr::AttrSet(attr_set) => {
+            if attr_set.rec_token().is_some() {
+                scopes.push(Scope::Bindings(collect_bindings(attr_set)));
+                walk_binding_values(attr_set, scopes, references);
+                scopes.pop();
+            } else {
+                walk_binding_values(attr_set, scopes, references);
+            }
+        }
+        ast::Expr::LetIn(let_in) => {
+            scopes.push(Scope::Bindings(collect_bindings(let_in)));
+            walk_binding_values(let_in, scopes, references);
+            if let Some(body) = let_in.body() {
+                walk(&body, scopes, references);
+            }
+            scopes.pop();
+        }
+        ast::Expr::Lambda(lambda) => {
+            let param = lambda.param();
+            scopes.push(Scope::Bindings(
+                param.as_ref().map(collect_param_bindings).unwrap_or_default(),
+            ));
+            if let Some(param) = &param {
+                walk_param_defaults(param, scopes, references);
+            }
+            if let Some(body) = lambda.body() {
+                walk(&body, scopes, references);
+            }
+            scopes.pop();
+        }
+        ast::Expr::With(with) => {
+            if let Some(namespace) = with.namespace() {
+                walk(&namespace, scopes, references);
+                let expr_text = namespace.syntax().text().to_string();
+                scopes.push(Scope::With { expr_text });
+                if let Some(body) = with.body() {
+                    walk(&body, scopes, references);
+                }
+                scopes.pop();
+            } else if let Some(body) = with.body() {
+                walk(&body, scopes, references);
+            }
+        }
+        ast::Expr::Paren(paren) => {
+            if let Some(inner) = paren.expr() {
+                walk(&inner, scopes, references);
+            }
+        }
+        ast::Expr::Apply(apply) => {
+            if let Some(f) = apply.lambda() {
+                walk(&f, scopes, references);
+            }
+            if let Some(a) = apply.argument() {
+                walk(&a, scopes, references);
+            }
+        }
+        ast::Expr::IfElse(if_else) => {
+            if let Some(condition) = if_else.condition() {
+                walk(&condition, scopes, references);
+            }
+            if let Some(body) = if_else.body() {
+                walk(&body, scopes, references);
+            }
+            if let Some(else_body) = if_else.else_body() {
+                walk(&else_body, scopes, references);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `AttrSet`/`LetIn`共通の`HasEntry`から、この階層で束縛される名前を集める
+///
+/// 値の走査より前に全ての名前を集めるのは、`let`内の束縛同士が定義順に
+/// 依存せず相互参照できるため。`a.b.c = 1;`は`a = { b = { c = 1; }; };`の
+/// 脱糖なので、このスコープに実際に導入される名前は先頭セグメント（`a`）で
+/// あり、末尾（`c`）ではない
+fn collect_bindings<N: HasEntry>(node: &N) -> HashMap<String, TextRange> {
+    let mut names = HashMap::new();
+    for entry in node.attrpath_values() {
+        let Some(attrpath) = entry.attrpath() else {
+            continue;
+        };
+        if let Some(head) = attrpath.attrs().next() {
+            if let Some(name) = attr_name(&head) {
+                names.insert(name, head.syntax().text_range());
+            }
+        }
+    }
+    for inherit in node.inherits() {
+        for attr in inherit.attrs() {
+            if let Some(name) = attr_name(&attr) {
+                names.insert(name, attr.syntax().text_range());
+            }
+        }
+    }
+    names
+}
+
+fn walk_binding_values<N: HasEntry>(
+    node: &N,
+    scopes: &mut Vec<Scope>,
+    references: &mut Vec<ResolvedReference>,
+) {
+    for entry in node.attrpath_values() {
+        if let Some(value) = entry.value() {
+            walk(&value, scopes, references);
+        }
+    }
+    for inherit in node.inherits() {
+        if let Some(from) = inherit.from() {
+            if let Some(from_expr) = from.expr() {
+                walk(&from_expr, scopes, references);
+            }
+        }
+    }
+}
+
+fn collect_param_bindings(param: &ast::Param) -> HashMap<String, TextRange> {
+    let mut names = HashMap::new();
+    match param {
+        ast::Param::IdentParam(ident_param) => {
+            if let Some(ident) = ident_param.ident() {
+                insert_ident(&ident, &mut names);
+            }
+        }
+        ast::Param::Pattern(pattern) => {
+            if let Some(bind) = pattern.pat_bind() {
+                if let Some(ident) = bind.ident() {
+                    insert_ident(&ident, &mut names);
+                }
+            }
+            for entry in pattern.pat_entries() {
+                if let Some(ident) = entry.ident() {
+                    insert_ident(&ident, &mut names);
+                }
+            }
+        }
+    }
+    names
+}
+
+fn walk_param_defaults(
+    param: &ast::Param,
+    scopes: &mut Vec<Scope>,
+    references: &mut Vec<ResolvedReference>,
+) {
+    if let ast::Param::Pattern(pattern) = param {
+        for entry in pattern.pat_entries() {
+            if let Some(default) = entry.default() {
+                walk(&default, scopes, references);
+            }
+        }
+    }
+}
+
+fn insert_ident(ident: &ast::Ident, names: &mut HashMap<String, TextRange>) {
+    if let Some(token) = ident.ident_token() {
+        names.insert(token.text().to_string(), ident.syntax().text_range());
+    }
+}
+
+fn attr_name(attr: &ast::Attr) -> Option<String> {
+    match attr {
+        ast::Attr::Ident(ident) => ident.ident_token().map(|t| t.text().to_string()),
+        ast::Attr::Dynamic(_) | ast::Attr::Str(_) => None,
+    }
+}
+
+/// スタック全体から字句束縛を探し、なければ最も内側の`with`に帰属させる
+///
+/// 字句束縛は`with`が字句的にどれだけ近くにあっても常に優先される。これは
+/// 誤りやすい点だが、Nix自身の挙動（`with`は他の場所で束縛されていない名前
+/// のみを補う）と一致させるため必須の順序
+fn resolve_name(name: &str, scopes: &[Scope]) -> Resolution {
+    for scope in scopes.iter().rev() {
+        if let Scope::Bindings(names) = scope {
+            if let Some(range) = names.get(name) {
+                return Resolution::Binding(*range);
+            }
+        }
+    }
+
+    for scope in scopes.iter().rev() {
+        if let Scope::With { expr_text } = scope {
+            return Resolution::WithScope {
+                expr_text: expr_text.clone(),
+            };
+        }
+    }
+
+    Resolution::Unresolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolution_of<'a>(refs: &'a [ResolvedReference], name: &str) -> &'a Resolution {
+        &refs
+            .iter()
+            .find(|r| r.name == name)
+            .unwrap_or_else(|| panic!("no reference to '{}' found", name))
+            .resolution
+    }
+
+    #[test]
+    fn test_let_binding_resolves_to_its_own_range() {
+        let source = "let x = 1; in x";
+        let refs = resolve_references(source);
+        assert!(matches!(resolution_of(&refs, "x"), Resolution::Binding(_)));
+    }
+
+    #[test]
+    fn test_inner_let_shadows_outer_let() {
+        // 束縛自体の名前（attrpath）はIdent式として歩かれないため、`references`に
+        // 現れるのは本文で使われる最後の`x`のみ
+        let source = "let x = 1; in let x = 2; in x";
+        let refs = resolve_references(source);
+        let Resolution::Binding(range) = resolution_of(&refs, "x") else {
+            panic!("expected x to resolve to a binding");
+        };
+        // 内側の`let x = 2;`（オフセット18）に解決され、外側（オフセット4）ではない
+        assert_eq!(range.start(), rowan::TextSize::from(18));
+    }
+
+    #[test]
+    fn test_with_scope_is_used_when_no_lexical_binding_exists() {
+        let source = "with pkgs; hello";
+        let refs = resolve_references(source);
+        assert!(matches!(
+            resolution_of(&refs, "hello"),
+            Resolution::WithScope { .. }
+        ));
+    }
+
+    #[test]
+    fn test_lexical_binding_wins_over_with_scope() {
+        let source = "let hello = 1; in with pkgs; hello";
+        let refs = resolve_references(source);
+        assert!(matches!(
+            resolution_of(&refs, "hello"),
+            Resolution::Binding(_)
+        ));
+    }
+
+    #[test]
+    fn test_lambda_pattern_introduces_parameter_bindings() {
+        let source = "{ a, b ? 1 }: a";
+        let refs = resolve_references(source);
+        assert!(matches!(resolution_of(&refs, "a"), Resolution::Binding(_)));
+    }
+
+    #[test]
+    fn test_inherit_from_introduces_binding_and_resolves_source_expr() {
+        let source = "let inherit (pkgs) hello; in hello";
+        let refs = resolve_references(source);
+        assert!(matches!(
+            resolution_of(&refs, "hello"),
+            Resolution::Binding(_)
+        ));
+        // `pkgs` 自体は`inherit`節の外側にある参照として解決される
+        assert!(matches!(resolution_of(&refs, "pkgs"), Resolution::Unresolved));
+    }
+
+    #[test]
+    fn test_dotted_attrpath_binds_its_first_segment() {
+        // `devShells.default = ...;`は`devShells = { default = ...; };`の脱糖なので、
+        // 外側スコープに導入されるのは`devShells`であって`default`ではない
+        let source = "let devShells.default = 1; in devShells";
+        let refs = resolve_references(source);
+        assert!(matches!(
+            resolution_of(&refs, "devShells"),
+            Resolution::Binding(_)
+        ));
+    }
+}
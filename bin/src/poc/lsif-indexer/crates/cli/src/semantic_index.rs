@@ -0,0 +1,186 @@
+//! シンボルのセマンティック検索（埋め込みベース）
+//!
+//! `search_symbols` の字句マッチでは「データベース接続を開く関数」のような
+//! 自然文クエリを扱えない。ここでは永続化時にシンボルごとの埋め込みベクトルを
+//! 計算して `IndexStorage` に保存し、検索時にはクエリを埋め込んでコサイン類似度
+//! でランキングする。埋め込みエンドポイントはプラガブルなHTTPの
+//! リクエスト/レスポンス契約（ローカル・リモートどちらのモデルでも可）。
+use anyhow::{Context, Result};
+use lsif_core::{CodeGraph, Symbol};
+use crate::storage::IndexStorage;
+use std::path::Path;
+
+/// 埋め込みを取得するHTTPエンドポイント
+///
+/// リクエスト: `{"input": "<text>"}`
+/// レスポンス: `{"embedding": [f32, ...]}`
+pub struct EmbeddingEndpoint {
+    url: String,
+}
+
+impl EmbeddingEndpoint {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let response = ureq::post(&self.url)
+            .send_json(serde_json::json!({ "input": text }))
+            .context("embedding endpoint request failed")?;
+
+        let body: serde_json::Value =
+            response.into_json().context("invalid embedding response body")?;
+
+        let embedding = body
+            .get("embedding")
+            .and_then(|e| e.as_array())
+            .ok_or_else(|| anyhow::anyhow!("embedding response missing `embedding` array"))?
+            .iter()
+            .filter_map(|v| v.as_f64().map(|f| f as f32))
+            .collect();
+
+        Ok(embedding)
+    }
+}
+
+/// シンボル1件分の埋め込みベクトル。グラフと並べて `IndexStorage` に保存される。
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SymbolEmbedding {
+    pub symbol_id: String,
+    pub vector: Vec<f32>,
+}
+
+/// 永続化時にシンボルの埋め込みを計算・保存するサブシステム
+pub struct SemanticIndex {
+    endpoint: EmbeddingEndpoint,
+}
+
+impl SemanticIndex {
+    pub fn new(endpoint: EmbeddingEndpoint) -> Self {
+        Self { endpoint }
+    }
+
+    /// 各シンボルの 名前 + シグネチャ + doc comment を埋め込み、ストレージに保存する
+    ///
+    /// 個々のシンボルの埋め込みに失敗しても全体は止めず、警告を出してスキップする。
+    pub fn persist_embeddings(&self, graph: &CodeGraph, storage_path: &Path, key: &str) -> Result<()> {
+        let storage = IndexStorage::open(storage_path)?;
+        let mut embeddings = Vec::new();
+
+        for symbol in graph.symbols() {
+            match self.endpoint.embed(&embedding_text(symbol)) {
+                Ok(vector) => embeddings.push(SymbolEmbedding {
+                    symbol_id: symbol.id.clone(),
+                    vector,
+                }),
+                Err(e) => {
+                    tracing::warn!("failed to embed symbol '{}': {}", symbol.name, e);
+                }
+            }
+        }
+
+        storage.store_embeddings(key, &embeddings)
+    }
+}
+
+fn embedding_text(symbol: &Symbol) -> String {
+    let mut parts = vec![symbol.name.clone()];
+    if let Some(signature) = &symbol.signature {
+        parts.push(signature.clone());
+    }
+    if let Some(doc) = &symbol.documentation {
+        parts.push(doc.clone());
+    }
+    parts.join("\n")
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// セマンティック検索。クエリを埋め込み、保存済みベクトルとのコサイン類似度で
+/// 上位 `top_k` 件の `Symbol` を返す。埋め込みが保存されていない、または
+/// エンドポイントに到達できない場合は既存の字句検索にフォールバックする。
+pub fn search_symbols_semantic(
+    endpoint: &EmbeddingEndpoint,
+    storage_path: &Path,
+    key: &str,
+    query: &str,
+    top_k: usize,
+) -> Result<Vec<Symbol>> {
+    let storage = IndexStorage::open_read_only(storage_path)
+        .context("failed to open index storage in read-only mode")?;
+
+    let stored_embeddings = storage.load_embeddings(key)?;
+    let query_vector = endpoint.embed(query);
+
+    let (stored_embeddings, query_vector) = match (stored_embeddings, query_vector) {
+        (Some(embeddings), Ok(vector)) if !embeddings.is_empty() => (embeddings, vector),
+        _ => {
+            tracing::debug!("falling back to lexical search_symbols for query '{}'", query);
+            return crate::persistence_helper::search_symbols(storage_path, key, query);
+        }
+    };
+
+    let Some(graph) = storage.load_graph(key)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut scored: Vec<(f32, &Symbol)> = stored_embeddings
+        .iter()
+        .filter_map(|embedding| {
+            let symbol = graph.symbols().find(|s| s.id == embedding.symbol_id)?;
+            Some((cosine_similarity(&query_vector, &embedding.vector), symbol))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    Ok(scored
+        .into_iter()
+        .take(top_k)
+        .map(|(_, symbol)| symbol.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence_helper;
+    use lsif_core::{Position, Range, SymbolKind};
+
+    #[test]
+    fn test_search_symbols_semantic_falls_back_to_lexical_without_embeddings() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut graph = CodeGraph::new();
+        graph.add_symbol(Symbol {
+            id: "open_db_connection@0:0".to_string(),
+            name: "open_db_connection".to_string(),
+            kind: SymbolKind::Function,
+            range: Range {
+                start: Position { line: 0, character: 0 },
+                end: Position { line: 0, character: 3 },
+            },
+            file_path: "a.rs".to_string(),
+            signature: None,
+            documentation: None,
+        });
+        persistence_helper::persist_index_result(&graph, dir.path(), "k").unwrap();
+
+        // 埋め込みは一切保存していないので、エンドポイントに到達できなくても
+        // 既存の字句検索にフォールバックできるはず
+        let endpoint = EmbeddingEndpoint::new("http://127.0.0.1:0/embed");
+        let results =
+            search_symbols_semantic(&endpoint, dir.path(), "k", "open_db_connection", 10).unwrap();
+        assert!(results.iter().any(|s| s.name == "open_db_connection"));
+    }
+}
@@ -0,0 +1,230 @@
+//! `CodeGraph`・埋め込みベクトルのファイルベース永続化ストレージ
+//!
+//! キーごとに1ファイルへbincodeでシリアライズして保存する。書き込みは
+//! 排他ロック（`flock` EX）、読み取りは共有ロック（`flock` SH）を取るため、
+//! エディタプロセスなどが書き込みハンドルを保持していても、複数の読み取り
+//! 専用ハンドル（[`IndexStorage::open_read_only`]）は同時にロードできる。
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use lsif_core::{CodeGraph, Symbol};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::semantic_index::SymbolEmbedding;
+
+/// 永続化ストレージへのハンドル
+///
+/// [`IndexStorage::open`] で開いたハンドルは読み書き両方ができるが、
+/// [`IndexStorage::open_read_only`] で開いたハンドルは `read_only` が `true`
+/// になり、`store_graph`/`store_embeddings` はディスクに触れる前に `Err` を
+/// 返す。
+pub struct IndexStorage {
+    root: PathBuf,
+    read_only: bool,
+}
+
+impl IndexStorage {
+    /// 読み書き両用でストレージを開く。`root` 以下にキーごとのファイルを作る
+    pub fn open(root: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root)
+            .with_context(|| format!("failed to create index storage dir {}", root.display()))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            read_only: false,
+        })
+    }
+
+    /// 読み取り専用でストレージを開く
+    ///
+    /// 書き込み側（エディタプロセスなど）がこのストアを保持していても、
+    /// 共有ロックで並行して読み込める。書き込みを試みるハンドルは
+    /// `store_graph`/`store_embeddings` を呼んだ時点で `Err` になる。
+    pub fn open_read_only(root: &Path) -> Result<Self> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            read_only: true,
+        })
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!(
+                "index storage handle at {} is read-only; open with `IndexStorage::open` to write",
+                self.root.display()
+            );
+        }
+        Ok(())
+    }
+
+    fn path_for(&self, key: &str, suffix: &str) -> PathBuf {
+        self.root.join(format!("{}.{}.bin", key, suffix))
+    }
+
+    fn write_locked(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        // `.truncate(true)` at `open()` time would zero the file before we hold
+        // the exclusive lock, letting a concurrent `read_locked` observe a
+        // truncated file. Open without truncating, take the lock, then
+        // truncate explicitly while we hold it.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("failed to open {} for writing", path.display()))?;
+        file.lock_exclusive()
+            .with_context(|| format!("failed to lock {} exclusively", path.display()))?;
+        let result = file
+            .set_len(0)
+            .and_then(|_| file.write_all(bytes))
+            .with_context(|| format!("failed to write {}", path.display()));
+        FileExt::unlock(&file).ok();
+        result
+    }
+
+    /// 共有ロックを取ってから読むため、書き手が排他ロックを保持している
+    /// コミット途中の内容は観測しない。ファイルが存在しなければ `None`、
+    /// それ以外の失敗は `Err` として返し、パニックはしない
+    fn read_locked(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to open {}", path.display())),
+        };
+        file.lock_shared()
+            .with_context(|| format!("failed to lock {} for reading", path.display()))?;
+        let mut bytes = Vec::new();
+        let result = (&file)
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("failed to read {}", path.display()));
+        FileExt::unlock(&file).ok();
+        result?;
+        Ok(Some(bytes))
+    }
+
+    pub fn store_graph(&self, key: &str, graph: &CodeGraph) -> Result<()> {
+        self.ensure_writable()?;
+        let path = self.path_for(key, "graph");
+        let bytes = bincode::serialize(graph).context("failed to serialize CodeGraph")?;
+        self.write_locked(&path, &bytes)
+    }
+
+    pub fn load_graph(&self, key: &str) -> Result<Option<CodeGraph>> {
+        let path = self.path_for(key, "graph");
+        let Some(bytes) = self.read_locked(&path)? else {
+            return Ok(None);
+        };
+        let graph = bincode::deserialize(&bytes).context("failed to deserialize CodeGraph")?;
+        Ok(Some(graph))
+    }
+
+    pub fn search_symbols(&self, key: &str, pattern: &str) -> Result<Vec<Symbol>> {
+        let Some(graph) = self.load_graph(key)? else {
+            return Ok(Vec::new());
+        };
+        Ok(graph
+            .symbols()
+            .filter(|s| s.name.contains(pattern))
+            .cloned()
+            .collect())
+    }
+
+    pub fn store_embeddings(&self, key: &str, embeddings: &[SymbolEmbedding]) -> Result<()> {
+        self.ensure_writable()?;
+        let path = self.path_for(key, "embeddings");
+        let bytes = bincode::serialize(embeddings).context("failed to serialize embeddings")?;
+        self.write_locked(&path, &bytes)
+    }
+
+    pub fn load_embeddings(&self, key: &str) -> Result<Option<Vec<SymbolEmbedding>>> {
+        let path = self.path_for(key, "embeddings");
+        let Some(bytes) = self.read_locked(&path)? else {
+            return Ok(None);
+        };
+        let embeddings =
+            bincode::deserialize(&bytes).context("failed to deserialize embeddings")?;
+        Ok(Some(embeddings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsif_core::{Position, Range, SymbolKind};
+
+    fn sample_graph() -> CodeGraph {
+        let mut graph = CodeGraph::new();
+        graph.add_symbol(Symbol {
+            id: "foo@0:0".to_string(),
+            name: "foo".to_string(),
+            kind: SymbolKind::Function,
+            range: Range {
+                start: Position { line: 0, character: 0 },
+                end: Position { line: 0, character: 3 },
+            },
+            file_path: "a.rs".to_string(),
+            signature: None,
+            documentation: None,
+        });
+        graph
+    }
+
+    #[test]
+    fn test_store_and_load_graph_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = IndexStorage::open(dir.path()).unwrap();
+        storage.store_graph("k", &sample_graph()).unwrap();
+
+        let loaded = storage.load_graph("k").unwrap().unwrap();
+        assert_eq!(loaded.symbols().count(), 1);
+    }
+
+    #[test]
+    fn test_read_only_handle_rejects_store_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = IndexStorage::open_read_only(dir.path()).unwrap();
+        assert!(storage.store_graph("k", &sample_graph()).is_err());
+    }
+
+    #[test]
+    fn test_read_only_handle_loads_data_written_by_writer_handle() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = IndexStorage::open(dir.path()).unwrap();
+        writer.store_graph("k", &sample_graph()).unwrap();
+
+        let reader = IndexStorage::open_read_only(dir.path()).unwrap();
+        let loaded = reader.load_graph("k").unwrap();
+        assert!(loaded.is_some());
+    }
+
+    #[test]
+    fn test_load_graph_for_missing_key_returns_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = IndexStorage::open(dir.path()).unwrap();
+        assert!(storage.load_graph("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_overwriting_with_a_smaller_payload_leaves_no_stale_trailing_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = IndexStorage::open(dir.path()).unwrap();
+
+        let mut larger = sample_graph();
+        larger.add_symbol(Symbol {
+            id: "bar@1:0".to_string(),
+            name: "bar".to_string(),
+            kind: SymbolKind::Function,
+            range: Range {
+                start: Position { line: 1, character: 0 },
+                end: Position { line: 1, character: 3 },
+            },
+            file_path: "a.rs".to_string(),
+            signature: None,
+            documentation: None,
+        });
+        storage.store_graph("k", &larger).unwrap();
+        storage.store_graph("k", &sample_graph()).unwrap();
+
+        let loaded = storage.load_graph("k").unwrap().unwrap();
+        assert_eq!(loaded.symbols().count(), 1);
+    }
+}
@@ -1,8 +1,88 @@
 use super::language::{DefinitionPattern, LanguageAdapter, PatternType};
 use super::lsp::LspAdapter;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
 use std::process::{Command, Child, Stdio};
+use std::time::{Duration, Instant};
 use lsp_types::{ClientInfo, InitializeParams, Url, WorkDoneProgressParams, WorkspaceFolder};
+use tracing::{debug, warn};
+
+/// ストア上の依存関係がビルド時（`.drv`）かランタイムかを表す
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    Build,
+    Runtime,
+}
+
+/// Nixストアのクロージャ内で見つかった1件の依存
+#[derive(Debug, Clone)]
+pub struct StoreDependency {
+    pub path: String,
+    pub kind: DependencyKind,
+}
+
+/// `nix flake archive --json` で解決された1つのflake input
+///
+/// `nixpkgs.legacyPackages.${system}` のように識別子の先頭セグメントが
+/// 他のflakeを指している場合、この `store_path` を外部ワークスペースルート
+/// として扱うことで、その input の `flake.nix`/`default.nix` をインデックス
+/// し定義ジャンプを辿れるようにする。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedInput {
+    pub name: String,
+    pub store_path: String,
+}
+
+/// バイトオフセットと、`builtins.unsafeGetAttrPos` が返す1-basedの
+/// `line`/`column` からバイトオフセットを求めるための行頭オフセット索引
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        for (idx, ch) in source.char_indices() {
+            if ch == '\n' {
+                line_starts.push(idx + 1);
+            }
+        }
+        Self { line_starts }
+    }
+
+    /// 1-basedの `line`/`column` をバイトオフセットに変換する。範囲外の行は `None`
+    pub fn offset(&self, line: u32, column: u32) -> Option<usize> {
+        let line_start = *self.line_starts.get(line.checked_sub(1)? as usize)?;
+        Some(line_start + column.saturating_sub(1) as usize)
+    }
+}
+
+/// `nix eval` による意味解析で求めた、ある属性の実際の定義位置
+///
+/// 静的な構文木（`cli` クレートの `fallback_indexer` 相当）では `import`・`//`マージ・
+/// `callPackage` を跨いだ定義元を追えないため、実際に評価して
+/// `builtins.unsafeGetAttrPos` から取得したもの。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticDefinition {
+    pub name: String,
+    pub file: String,
+    pub byte_offset: usize,
+    /// 現在開いているドキュメントとは別ファイルで定義されている場合 `true`
+    pub is_cross_file: bool,
+}
+
+/// `flake.lock` で解決済みのflake input（推移的なものも含む）
+#[derive(Debug, Clone)]
+pub struct LockedInput {
+    pub name: String,
+    pub node_key: String,
+    pub input_type: Option<String>,
+    pub owner: Option<String>,
+    pub repo: Option<String>,
+    pub rev: Option<String>,
+    pub last_modified: Option<i64>,
+}
 
 pub struct NixdAdapter;
 
@@ -10,6 +90,456 @@ impl NixdAdapter {
     pub fn new() -> Self {
         Self
     }
+
+    /// `flake.nix` の `inputs` ブロックをテキスト解析し、`name -> url` を抽出する
+    pub fn parse_flake_inputs(&self, content: &str) -> Vec<(String, String)> {
+        let re = regex::Regex::new(r#"(?m)^\s*([A-Za-z0-9_-]+)\.url\s*=\s*"([^"]+)"\s*;"#)
+            .expect("static flake input regex is valid");
+
+        re.captures_iter(content)
+            .map(|cap| (cap[1].to_string(), cap[2].to_string()))
+            .collect()
+    }
+
+    /// `flake.lock` を解析し、rootから辿れる推移的なinput一覧を返す
+    ///
+    /// `nodes` マップを `root` から辿り、各ノードの `inputs` が指す先（単純な
+    /// ノード名、またはfollows指定の経路配列）を解決しながら `locked` 情報
+    /// （`rev`/`lastModified`/…）を集める。
+    pub fn parse_flake_lock(&self, content: &str) -> Result<Vec<LockedInput>> {
+        let value: serde_json::Value =
+            serde_json::from_str(content).context("invalid flake.lock JSON")?;
+        let nodes = value
+            .get("nodes")
+            .and_then(|n| n.as_object())
+            .ok_or_else(|| anyhow::anyhow!("flake.lock missing `nodes`"))?;
+        let root = value.get("root").and_then(|r| r.as_str()).unwrap_or("root");
+
+        let mut visited: HashSet<String> = HashSet::new();
+        visited.insert(root.to_string());
+        let mut queue: VecDeque<String> = VecDeque::from([root.to_string()]);
+        let mut inputs = Vec::new();
+
+        while let Some(node_key) = queue.pop_front() {
+            let Some(node_inputs) = nodes
+                .get(&node_key)
+                .and_then(|n| n.get("inputs"))
+                .and_then(|i| i.as_object())
+            else {
+                continue;
+            };
+
+            for (name, target) in node_inputs {
+                let resolved_key = match target {
+                    serde_json::Value::String(key) => Some(key.clone()),
+                    serde_json::Value::Array(path) => {
+                        let path: Vec<String> = path
+                            .iter()
+                            .filter_map(|segment| segment.as_str().map(String::from))
+                            .collect();
+                        resolve_follows_path(nodes, root, &path)
+                    }
+                    _ => None,
+                };
+
+                let Some(resolved_key) = resolved_key else {
+                    continue;
+                };
+                let Some(target_node) = nodes.get(&resolved_key) else {
+                    continue;
+                };
+
+                let locked = target_node.get("locked");
+                inputs.push(LockedInput {
+                    name: name.clone(),
+                    node_key: resolved_key.clone(),
+                    input_type: field_str(locked, "type"),
+                    owner: field_str(locked, "owner"),
+                    repo: field_str(locked, "repo"),
+                    rev: field_str(locked, "rev"),
+                    last_modified: locked.and_then(|l| l.get("lastModified")).and_then(|t| t.as_i64()),
+                });
+
+                if visited.insert(resolved_key.clone()) {
+                    queue.push_back(resolved_key);
+                }
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    /// `flake.nix`（静的解析）と `flake.lock`（ある場合）から依存グラフを構築する
+    ///
+    /// 完全な推移的閉包が必要な場合は
+    /// [`NixdAdapter::build_dependency_graph_from_store`] を使うこと。
+    pub fn build_dependency_graph(
+        &self,
+        _client: &mut crate::lsp_client::LspClient,
+        project_root: &Path,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let mut graph = Vec::new();
+
+        let flake_path = project_root.join("flake.nix");
+        if flake_path.exists() {
+            let content = std::fs::read_to_string(&flake_path)
+                .with_context(|| format!("failed to read {}", flake_path.display()))?;
+            let inputs = self.parse_flake_inputs(&content);
+
+            if !inputs.is_empty() {
+                let locked_revs: std::collections::HashMap<String, String> = project_root
+                    .join("flake.lock")
+                    .exists()
+                    .then(|| std::fs::read_to_string(project_root.join("flake.lock")).ok())
+                    .flatten()
+                    .and_then(|content| self.parse_flake_lock(&content).ok())
+                    .map(|locked| {
+                        locked
+                            .into_iter()
+                            .filter_map(|input| input.rev.map(|rev| (input.name, rev)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                let labeled = inputs
+                    .into_iter()
+                    .map(|(name, _url)| match locked_revs.get(&name) {
+                        Some(rev) => format!("{}@{}", name, truncate_rev(rev, 12)),
+                        None => name,
+                    })
+                    .collect();
+
+                graph.push((flake_path.display().to_string(), labeled));
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Nixストアのクロージャから依存グラフを構築する（`nix path-info --json --recursive` 相当）
+    ///
+    /// `flake.nix` に書かれたinput名だけを見る [`NixdAdapter::build_dependency_graph`]
+    /// と異なり、実際にビルド/実行時に参照されるストアパスの完全な推移的閉包を返す。
+    /// ハッシュ部分（store pathの32文字のプレフィックス）で重複排除する。
+    pub fn build_dependency_graph_from_store(
+        &self,
+        store_path_or_flake: &str,
+    ) -> Result<Vec<(String, Vec<StoreDependency>)>> {
+        let output = Command::new("nix")
+            .args(["path-info", "--json", "--recursive", store_path_or_flake])
+            .output()
+            .context("failed to run `nix path-info --json --recursive`")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "`nix path-info` exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
+            .context("failed to parse `nix path-info --json` output")?;
+
+        let mut seen_hashes: HashSet<String> = HashSet::new();
+        let mut graph = Vec::new();
+
+        for entry in &entries {
+            let path = entry
+                .get("path")
+                .and_then(|p| p.as_str())
+                .ok_or_else(|| anyhow::anyhow!("store entry missing `path`"))?;
+
+            if !seen_hashes.insert(store_hash_prefix(path)) {
+                continue;
+            }
+
+            let deps = entry
+                .get("references")
+                .and_then(|r| r.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|r| r.as_str())
+                .filter(|r| *r != path)
+                .map(|r| StoreDependency {
+                    path: r.to_string(),
+                    kind: if r.ends_with(".drv") {
+                        DependencyKind::Build
+                    } else {
+                        DependencyKind::Runtime
+                    },
+                })
+                .collect();
+
+            graph.push((path.to_string(), deps));
+        }
+
+        Ok(graph)
+    }
+
+    /// `nix flake archive --json` でflake inputsを解決し、属性名ごとの
+    /// フェッチ済みストアパスを返す（クロスflakeのgo-to-definition用）
+    ///
+    /// archiveはinput名をネストした `inputs` マップとして返すが、follows
+    /// 指定は展開済みのノードとして返るため、`flake.lock` 側が使う名前との
+    /// 対応が失われることがある。[`NixdAdapter::reconcile_with_lock`] で
+    /// `flake.lock` と突き合わせ、followsのエイリアス名でも同じstore pathを
+    /// 引けるようにする。
+    pub fn resolve_flake_inputs(&self, flake_dir: &Path) -> Result<Vec<ResolvedInput>> {
+        let flake_url = flake_dir.display().to_string();
+
+        let output = Command::new("nix")
+            .args([
+                "flake",
+                "archive",
+                "--extra-experimental-features",
+                "nix-command flakes",
+                "--json",
+                &flake_url,
+            ])
+            .stdin(Stdio::null())
+            .output()
+            .context("failed to run `nix flake archive --json`")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "`nix flake archive --json` exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let stdout = String::from_utf8(output.stdout)
+            .context("`nix flake archive --json` produced non-UTF8 output")?;
+        let mut resolved = self.parse_flake_archive(&stdout)?;
+        self.reconcile_with_lock(flake_dir, &mut resolved);
+
+        Ok(resolved)
+    }
+
+    /// `nix flake archive --json` の出力（`{"path": ..., "inputs": {...}}`）を
+    /// 解析し、`self` と各input名をそのstore pathに対応付ける
+    pub fn parse_flake_archive(&self, content: &str) -> Result<Vec<ResolvedInput>> {
+        let archive: serde_json::Value =
+            serde_json::from_str(content).context("invalid `nix flake archive --json` output")?;
+
+        let mut resolved = Vec::new();
+        if let Some(self_path) = archive.get("path").and_then(|p| p.as_str()) {
+            resolved.push(ResolvedInput {
+                name: "self".to_string(),
+                store_path: self_path.to_string(),
+            });
+        }
+
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<(String, &serde_json::Value)> = VecDeque::new();
+        if let Some(inputs) = archive.get("inputs").and_then(|i| i.as_object()) {
+            for (name, node) in inputs {
+                queue.push_back((name.clone(), node));
+            }
+        }
+
+        while let Some((name, node)) = queue.pop_front() {
+            if let Some(path) = node.get("path").and_then(|p| p.as_str()) {
+                if seen.insert(name.clone()) {
+                    resolved.push(ResolvedInput {
+                        name,
+                        store_path: path.to_string(),
+                    });
+                }
+            }
+
+            if let Some(nested) = node.get("inputs").and_then(|i| i.as_object()) {
+                for (nested_name, nested_node) in nested {
+                    queue.push_back((nested_name.clone(), nested_node));
+                }
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// `flake.lock` にしか現れないinput名（followsのエイリアス）を、
+    /// 同じノードを指す既存の解決結果から補って追加する
+    fn reconcile_with_lock(&self, flake_dir: &Path, resolved: &mut Vec<ResolvedInput>) {
+        let lock_path = flake_dir.join("flake.lock");
+        let Ok(content) = std::fs::read_to_string(&lock_path) else {
+            return;
+        };
+        let Ok(locked_inputs) = self.parse_flake_lock(&content) else {
+            return;
+        };
+
+        for locked in locked_inputs {
+            if resolved.iter().any(|r| r.name == locked.name) {
+                continue;
+            }
+            if let Some(store_path) = resolved
+                .iter()
+                .find(|r| r.name == locked.node_key)
+                .map(|r| r.store_path.clone())
+            {
+                resolved.push(ResolvedInput {
+                    name: locked.name,
+                    store_path,
+                });
+            }
+        }
+    }
+
+    /// `set_expr` が評価する属性セットについて、`attribute_names` それぞれの
+    /// 実際の定義位置を `builtins.unsafeGetAttrPos` で求める
+    ///
+    /// 静的抽出では見えない `import`/`//`マージ/`callPackage` 越しの定義元に
+    /// 対応するためのオプションの「意味解析」モード。`current_document` と
+    /// 異なるファイルで定義されている属性は [`SemanticDefinition::is_cross_file`]
+    /// が `true` になる。評価の失敗・タイムアウト、または位置情報を持たない
+    /// 属性（`unsafeGetAttrPos` が `null` を返す）は結果から静かに取り除かれ、
+    /// 呼び出し側は静的抽出（`cli` クレートの `fallback_indexer` 相当）にフォールバックできる。
+    pub fn resolve_semantic_positions(
+        &self,
+        set_expr: &str,
+        attribute_names: &[String],
+        current_document: &str,
+        timeout: Duration,
+    ) -> Vec<SemanticDefinition> {
+        attribute_names
+            .iter()
+            .filter_map(|name| self.eval_attr_pos(set_expr, name, current_document, timeout))
+            .collect()
+    }
+
+    fn eval_attr_pos(
+        &self,
+        set_expr: &str,
+        attribute_name: &str,
+        current_document: &str,
+        timeout: Duration,
+    ) -> Option<SemanticDefinition> {
+        let expr = format!(
+            r#"builtins.unsafeGetAttrPos "{}" ({})"#,
+            attribute_name.replace('"', "\\\""),
+            set_expr
+        );
+
+        let pos = run_nix_eval_json(&expr, timeout)?;
+        // 位置情報を持たない属性（組み込み関数の結果など）には `null` が返る
+        if pos.is_null() {
+            return None;
+        }
+
+        let file = pos.get("file")?.as_str()?.to_string();
+        let line = pos.get("line")?.as_u64()? as u32;
+        let column = pos.get("column")?.as_u64()? as u32;
+
+        let source = std::fs::read_to_string(&file).ok()?;
+        let byte_offset = LineIndex::new(&source).offset(line, column)?;
+
+        Some(SemanticDefinition {
+            name: attribute_name.to_string(),
+            is_cross_file: file != current_document,
+            file,
+            byte_offset,
+        })
+    }
+}
+
+/// `nix eval --json --expr <expr>` を実行し、結果のJSON値を返す
+///
+/// `timeout` を超えても終了しない場合はプロセスをkillして `None` を返し、
+/// 非0終了やJSONパース失敗も同様に `None` として扱う。呼び出し側（意味解析
+/// モード）はこれを「フォールバックすべきシグナル」として扱う。
+fn run_nix_eval_json(expr: &str, timeout: Duration) -> Option<serde_json::Value> {
+    let mut child = Command::new("nix")
+        .args([
+            "eval",
+            "--extra-experimental-features",
+            "nix-command flakes",
+            "--json",
+            "--expr",
+            expr,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_status)) => break,
+            Ok(None) => {
+                if start.elapsed() >= timeout {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    warn!("`nix eval --json` timed out after {:?}", timeout);
+                    return None;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(_) => return None,
+        }
+    }
+
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        debug!(
+            "`nix eval --json` failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return None;
+    }
+
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// `follows` の経路配列（例: `["utils", "nixpkgs"]`）を `root` から辿って実ノード名に解決する
+fn resolve_follows_path(
+    nodes: &serde_json::Map<String, serde_json::Value>,
+    root: &str,
+    path: &[String],
+) -> Option<String> {
+    let mut current = root.to_string();
+    for segment in path {
+        let next = nodes.get(&current)?.get("inputs")?.get(segment)?;
+        current = match next {
+            serde_json::Value::String(key) => key.clone(),
+            serde_json::Value::Array(sub_path) => {
+                let sub_path: Vec<String> = sub_path
+                    .iter()
+                    .filter_map(|s| s.as_str().map(String::from))
+                    .collect();
+                resolve_follows_path(nodes, root, &sub_path)?
+            }
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn field_str(value: Option<&serde_json::Value>, key: &str) -> Option<String> {
+    value
+        .and_then(|v| v.get(key))
+        .and_then(|v| v.as_str())
+        .map(String::from)
+}
+
+/// flake.lockの`rev`（外部リポジトリ由来でバイト列がUTF-8境界と限らない）から
+/// 先頭`max_chars`文字を安全に取り出す。バイト単位のスライスは不正な境界で
+/// パニックしうるため、`char`単位で数える
+pub fn truncate_rev(rev: &str, max_chars: usize) -> String {
+    rev.chars().take(max_chars).collect()
+}
+
+/// store pathの `/nix/store/<hash>-<name>` から32文字のハッシュプレフィックスを取り出す
+fn store_hash_prefix(store_path: &str) -> String {
+    Path::new(store_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.get(0..32))
+        .unwrap_or(store_path)
+        .to_string()
 }
 
 impl LanguageAdapter for NixdAdapter {
@@ -32,7 +562,10 @@ impl LanguageAdapter for NixdAdapter {
 
     fn definition_patterns(&self) -> Vec<DefinitionPattern> {
         // Nix doesn't have clear keyword-based patterns like other languages
-        // Definitions are usually attribute sets and function parameters
+        // Definitions are usually attribute sets and function parameters.
+        // The real (non-heuristic) extraction now lives in
+        // `fallback_indexer::FallbackIndexer`, which parses the whole file
+        // with rnix instead of probing single lines.
         vec![]
     }
 
@@ -64,7 +64,28 @@ fn main() -> anyhow::Result<()> {
         }
         Err(e) => eprintln!("  Error building dependency graph: {}", e),
     }
-    
+
+    // flake.nixに書かれたinput名だけでなく、実際にビルド/実行時に参照される
+    // ストアパスの完全な推移的閉包も表示する
+    if flake_path.exists() {
+        println!("\n🏪 Full transitive closure (nix path-info --recursive):");
+        match adapter.build_dependency_graph_from_store(&project_root.display().to_string()) {
+            Ok(closure) => {
+                if closure.is_empty() {
+                    println!("  No store paths found (is the flake built/realized?)");
+                } else {
+                    for (path, deps) in &closure {
+                        println!("\n  {}:", path);
+                        for dep in deps {
+                            println!("    └─> {} ({:?})", dep.path, dep.kind);
+                        }
+                    }
+                }
+            }
+            Err(e) => eprintln!("  Error building store-backed dependency graph: {}", e),
+        }
+    }
+
     // クリーンアップ
     println!("\nShutting down LSP server...");
     client.shutdown()?;
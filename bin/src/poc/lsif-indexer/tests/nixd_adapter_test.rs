@@ -1,4 +1,4 @@
-use lsp::adapter::nixd::NixdAdapter;
+use lsp::adapter::nixd::{truncate_rev, LineIndex, NixdAdapter};
 use lsp::adapter::language::LanguageAdapter;
 
 #[test]
@@ -19,11 +19,134 @@ fn test_nix_adapter_lsp_spawn() {
 #[test]
 fn test_nix_adapter_patterns() {
     let adapter = NixdAdapter::new();
-    
+
     // 最小実装では空のパターンを返す
     assert_eq!(adapter.definition_patterns().len(), 0);
-    
+
     // リファレンスパターンは基本的な単語境界マッチ
     let pattern = adapter.build_reference_pattern("nixpkgs", &lsif_core::SymbolKind::Variable);
     assert!(pattern.contains("nixpkgs"));
+}
+
+#[test]
+fn test_parse_flake_lock_resolves_transitive_inputs() {
+    let adapter = NixdAdapter::new();
+    let lock = r#"
+    {
+      "nodes": {
+        "nixpkgs": {
+          "locked": {
+            "type": "github",
+            "owner": "NixOS",
+            "repo": "nixpkgs",
+            "rev": "abcdef1234567890abcdef1234567890abcdef12",
+            "lastModified": 1700000000
+          }
+        },
+        "flake-utils": {
+          "inputs": { "nixpkgs": ["nixpkgs"] },
+          "locked": {
+            "type": "github",
+            "owner": "numtide",
+            "repo": "flake-utils",
+            "rev": "1234567890abcdef1234567890abcdef12345678",
+            "lastModified": 1690000000
+          }
+        },
+        "root": {
+          "inputs": { "nixpkgs": "nixpkgs", "flake-utils": "flake-utils" }
+        }
+      },
+      "root": "root",
+      "version": 7
+    }
+    "#;
+
+    let inputs = adapter.parse_flake_lock(lock).unwrap();
+    assert!(inputs.iter().any(|i| i.name == "nixpkgs" && i.rev.as_deref() == Some("abcdef1234567890abcdef1234567890abcdef12")));
+    assert!(inputs.iter().any(|i| i.name == "flake-utils" && i.repo.as_deref() == Some("flake-utils")));
+    // flake-utils自身のnixpkgs inputはfollowsで解決され、同じノードを指す
+    // （rootの`nixpkgs`と合わせて2件になる）
+    assert_eq!(
+        inputs.iter().filter(|i| i.name == "nixpkgs" && i.node_key == "nixpkgs").count(),
+        2
+    );
+}
+
+#[test]
+fn test_parse_flake_archive_maps_inputs_to_store_paths() {
+    let adapter = NixdAdapter::new();
+    let archive = r#"
+    {
+      "path": "/nix/store/aaaa-source",
+      "inputs": {
+        "nixpkgs": { "path": "/nix/store/bbbb-nixpkgs" },
+        "flake-utils": {
+          "path": "/nix/store/cccc-flake-utils",
+          "inputs": {
+            "nixpkgs": { "path": "/nix/store/bbbb-nixpkgs" }
+          }
+        }
+      }
+    }
+    "#;
+
+    let resolved = adapter.parse_flake_archive(archive).unwrap();
+    assert!(resolved
+        .iter()
+        .any(|r| r.name == "self" && r.store_path == "/nix/store/aaaa-source"));
+    assert!(resolved
+        .iter()
+        .any(|r| r.name == "nixpkgs" && r.store_path == "/nix/store/bbbb-nixpkgs"));
+    assert!(resolved
+        .iter()
+        .any(|r| r.name == "flake-utils" && r.store_path == "/nix/store/cccc-flake-utils"));
+}
+
+#[test]
+fn test_line_index_converts_unsafe_get_attr_pos_coordinates() {
+    let source = "{\n  foo = 1;\n  bar = 2;\n}\n";
+    let index = LineIndex::new(source);
+
+    // `builtins.unsafeGetAttrPos` は1-basedの line/column を返す
+    assert_eq!(index.offset(1, 1), Some(0));
+    assert_eq!(index.offset(2, 3), Some(source.find("foo").unwrap()));
+    assert_eq!(index.offset(3, 3), Some(source.find("bar").unwrap()));
+    // 存在しない行はNone
+    assert_eq!(index.offset(100, 1), None);
+}
+
+#[test]
+fn test_parse_flake_inputs() {
+    let adapter = NixdAdapter::new();
+    let content = r#"
+    {
+      inputs = {
+        nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+        flake-utils.url = "github:numtide/flake-utils";
+      };
+    }
+    "#;
+
+    let inputs = adapter.parse_flake_inputs(content);
+    assert_eq!(inputs.len(), 2);
+    assert!(inputs
+        .iter()
+        .any(|(name, url)| name == "nixpkgs" && url == "github:NixOS/nixpkgs/nixos-unstable"));
+    assert!(inputs
+        .iter()
+        .any(|(name, url)| name == "flake-utils" && url == "github:numtide/flake-utils"));
+}
+
+#[test]
+fn test_truncate_rev_does_not_panic_on_multi_byte_boundary() {
+    // マルチバイト文字が先頭12バイト目より前にあると、バイト単位のスライスは
+    // 文字境界を跨いでパニックする。`truncate_rev` は文字単位で数えるため安全。
+    let rev = "あいうえおかきくけこさしすせ";
+    assert_eq!(truncate_rev(rev, 12), "あいうえおかきくけこさし");
+}
+
+#[test]
+fn test_truncate_rev_shorter_than_max_is_unchanged() {
+    assert_eq!(truncate_rev("abc", 12), "abc");
 }
\ No newline at end of file